@@ -1,32 +1,477 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::process::Command;
-use std::env;
-use tauri::Manager;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::api::process::{Command as SidecarCommand, CommandChild, CommandEvent};
+use tauri::{
+    CustomMenuItem, Manager, RunEvent, SystemTray, SystemTrayEvent, SystemTrayMenu,
+    SystemTrayMenuItem, WindowBuilder, WindowEvent, WindowUrl,
+};
 
-#[tauri::command]
-fn start_python_server() {
-    std::thread::spawn(|| {
-        // Get the current directory (src-tauri) and go up one level to project root
-        let current_dir = env::current_dir().expect("Failed to get current directory");
-        let project_root = current_dir.parent().expect("Failed to get project root");
-        
-        Command::new(".venv/bin/python")
-            .arg("src/fidu_core/main.py")
-            .current_dir(project_root)
+/// How long to wait for the health check to start returning 200 before giving up
+/// and showing the main window anyway.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(30);
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long to give the backend to flush and exit on its own after a shutdown
+/// request before it gets force-killed.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Bounds each read and write on a backend socket so a connection that accepts
+/// but then hangs can't stall a caller indefinitely. Callers check their overall
+/// deadlines only between attempts, so one attempt can still run past them.
+const SOCKET_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Ceiling for the restart backoff in `start_python_server`.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Doubles `current`, capped at `MAX_BACKOFF`.
+fn next_backoff(current: Duration) -> Duration {
+    std::cmp::min(current * 2, MAX_BACKOFF)
+}
+
+/// Binds a free TCP port and immediately releases it so the Python process can
+/// take it over. Avoids hardcoding a port that may already be in use.
+fn allocate_port() -> std::io::Result<u16> {
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    listener.local_addr().map(|addr| addr.port())
+}
+
+/// Owns the Python sidecar process and keeps it alive for the lifetime of the app.
+///
+/// The backend ships as a PyInstaller-frozen binary at `src-tauri/binaries/fidu_core`,
+/// resolved through Tauri's sidecar mechanism (declared under `bundle.externalBin`
+/// in `tauri.conf.json`), which works the same way in a packaged build on
+/// Windows/macOS/Linux as it does in dev. The actual frozen binary is produced by
+/// the PyInstaller build step and isn't tracked in this repo; `new_sidecar` fails
+/// to resolve and `spawn_child` returns `Err` until that artifact has been built.
+struct PythonServer {
+    child: Mutex<Option<CommandChild>>,
+    running: AtomicBool,
+    port: AtomicU16,
+    // Set by `graceful_shutdown` before it asks the backend to exit, so the
+    // monitor thread can tell an expected exit from a crash and not restart it.
+    quitting: AtomicBool,
+    // Bumped on every `start_python_server` call so a monitor thread from a
+    // superseded restart can tell it no longer owns `child` and stop touching it.
+    generation: AtomicU64,
+}
+
+impl PythonServer {
+    fn new(port: u16) -> Self {
+        Self {
+            child: Mutex::new(None),
+            running: AtomicBool::new(false),
+            port: AtomicU16::new(port),
+            quitting: AtomicBool::new(false),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    fn port(&self) -> u16 {
+        self.port.load(Ordering::SeqCst)
+    }
+
+    fn spawn_child(port: u16) -> tauri::api::Result<(tauri::api::process::Receiver<CommandEvent>, CommandChild)> {
+        SidecarCommand::new_sidecar("fidu_core")?
+            .env("FIDU_SERVER_PORT", port.to_string())
             .spawn()
-            .expect("Failed to start Python server");
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    fn kill(&self) {
+        if let Some(child) = self.child.lock().unwrap().take() {
+            let _ = child.kill();
+        }
+        self.running.store(false, Ordering::SeqCst);
+    }
+
+    /// Claims a new generation for a `start_python_server` call, invalidating
+    /// whatever generation is currently live.
+    fn claim_generation(&self) -> u64 {
+        self.generation.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Whether `generation` has since been superseded by a later `start_python_server` call.
+    fn is_superseded(&self, generation: u64) -> bool {
+        self.generation.load(Ordering::SeqCst) != generation
+    }
+}
+
+/// Spawns the Python backend and watches it on a background thread, restarting it
+/// with exponential backoff if it ever dies unexpectedly.
+///
+/// Each call claims a new generation. If a later call supersedes this one (e.g.
+/// `restart_server` runs while this generation's backend happens to be dying on
+/// its own), this thread notices the generation mismatch and stops touching
+/// `state.child`/`state.running` instead of racing the newer monitor thread for
+/// them.
+fn start_python_server(state: Arc<PythonServer>) {
+    let generation = state.claim_generation();
+    let superseded = |state: &PythonServer| state.is_superseded(generation);
+
+    std::thread::spawn(move || {
+        let mut backoff = Duration::from_secs(1);
+
+        loop {
+            if superseded(&state) {
+                return;
+            }
+
+            let mut rx = match PythonServer::spawn_child(state.port()) {
+                Ok((rx, child)) => {
+                    let mut guard = state.child.lock().unwrap();
+                    if superseded(&state) {
+                        drop(guard);
+                        let _ = child.kill();
+                        return;
+                    }
+                    *guard = Some(child);
+                    drop(guard);
+                    state.running.store(true, Ordering::SeqCst);
+                    rx
+                }
+                Err(err) => {
+                    eprintln!("Failed to start Python server: {err}");
+                    std::thread::sleep(backoff);
+                    backoff = next_backoff(backoff);
+                    continue;
+                }
+            };
+            let spawned_at = Instant::now();
+
+            // Drain sidecar output/events until it exits, or `kill()`/a newer
+            // generation has already taken the child out from under us. Also
+            // false if `graceful_shutdown` set `quitting` first, so a backend
+            // that exits on its own during app shutdown isn't mistaken for a
+            // crash and restarted.
+            let died_unexpectedly = loop {
+                match rx.blocking_recv() {
+                    Some(CommandEvent::Stdout(line)) => println!("[fidu_core] {line}"),
+                    Some(CommandEvent::Stderr(line)) => eprintln!("[fidu_core] {line}"),
+                    Some(CommandEvent::Terminated(_)) | None => {
+                        let mut guard = state.child.lock().unwrap();
+                        if superseded(&state) {
+                            break false;
+                        }
+                        let had_child = guard.take().is_some();
+                        break had_child && !state.quitting.load(Ordering::SeqCst);
+                    }
+                    Some(_) => {}
+                }
+            };
+
+            if superseded(&state) {
+                return;
+            }
+            state.running.store(false, Ordering::SeqCst);
+
+            if !died_unexpectedly {
+                break;
+            }
+
+            // Only a backend that stayed up for a while starts over at 1s; one that
+            // keeps crashing right after spawning keeps doubling its wait.
+            if spawned_at.elapsed() >= MAX_BACKOFF {
+                backoff = Duration::from_secs(1);
+            }
+
+            eprintln!("Python server exited unexpectedly, restarting in {backoff:?}");
+            std::thread::sleep(backoff);
+            backoff = next_backoff(backoff);
+        }
     });
 }
 
+/// Single GET /health attempt. Returns true only on a 200 response.
+fn check_health(port: u16) -> bool {
+    let mut stream = match TcpStream::connect(("127.0.0.1", port)) {
+        Ok(stream) => stream,
+        Err(_) => return false,
+    };
+    let _ = stream.set_read_timeout(Some(SOCKET_TIMEOUT));
+    let _ = stream.set_write_timeout(Some(SOCKET_TIMEOUT));
+
+    let request = b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+    if stream.write_all(request).is_err() {
+        return false;
+    }
+
+    let mut response = String::new();
+    if stream.read_to_string(&mut response).is_err() {
+        return false;
+    }
+
+    is_healthy_response(&response)
+}
+
+/// Whether an HTTP response's status line is a 200, for HTTP/1.1 or HTTP/1.0.
+fn is_healthy_response(response: &str) -> bool {
+    response.starts_with("HTTP/1.1 200") || response.starts_with("HTTP/1.0 200")
+}
+
+/// Polls the health endpoint until it responds or `HEALTH_CHECK_TIMEOUT` elapses.
+/// Returns whether the server came up in time.
+fn wait_for_server_ready(port: u16) -> bool {
+    let deadline = Instant::now() + HEALTH_CHECK_TIMEOUT;
+    while Instant::now() < deadline {
+        if check_health(port) {
+            return true;
+        }
+        std::thread::sleep(HEALTH_CHECK_INTERVAL);
+    }
+    false
+}
+
+/// Fires a bare POST to the backend and waits for one read (up to 256 bytes) of
+/// its response, ignoring failures:
+/// by the time this is called the app may be mid-exit either way.
+fn send_backend_post(port: u16, path: &str) {
+    if let Ok(mut stream) = TcpStream::connect(("127.0.0.1", port)) {
+        let _ = stream.set_read_timeout(Some(SOCKET_TIMEOUT));
+        let _ = stream.set_write_timeout(Some(SOCKET_TIMEOUT));
+        let request =
+            format!("POST {path} HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        if stream.write_all(request.as_bytes()).is_ok() {
+            let mut buf = [0u8; 256];
+            let _ = stream.read(&mut buf);
+        }
+    }
+}
+
+/// Asks the backend to flush and exit, gives it `SHUTDOWN_TIMEOUT` to do so, and
+/// only then force-kills it. Protects in-flight writes to conversation data.
+///
+/// Sets `quitting` first so the monitor thread treats the backend exiting on
+/// its own as expected instead of restarting it mid-shutdown.
+fn graceful_shutdown(state: &PythonServer) {
+    if !state.is_running() {
+        return;
+    }
+    state.quitting.store(true, Ordering::SeqCst);
+    send_backend_post(state.port(), "/shutdown");
+
+    let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+    while Instant::now() < deadline {
+        if !state.is_running() {
+            return;
+        }
+        std::thread::sleep(SHUTDOWN_POLL_INTERVAL);
+    }
+
+    eprintln!("Python server did not shut down within {SHUTDOWN_TIMEOUT:?}, killing it");
+    state.kill();
+}
+
+fn build_tray_menu() -> SystemTrayMenu {
+    SystemTrayMenu::new()
+        .add_item(CustomMenuItem::new("show", "Show"))
+        .add_item(CustomMenuItem::new("hide", "Hide"))
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new("quit", "Quit"))
+}
+
+fn toggle_main_window(app: &tauri::AppHandle) {
+    if let Some(window) = app.get_window("main") {
+        if window.is_visible().unwrap_or(false) {
+            let _ = window.hide();
+        } else {
+            let _ = window.show();
+            let _ = window.set_focus();
+        }
+    }
+}
+
+fn on_tray_event(app: &tauri::AppHandle, event: SystemTrayEvent) {
+    match event {
+        SystemTrayEvent::LeftClick { .. } => toggle_main_window(app),
+        SystemTrayEvent::MenuItemClick { id, .. } => match id.as_str() {
+            "show" => {
+                if let Some(window) = app.get_window("main") {
+                    let _ = window.show();
+                    let _ = window.set_focus();
+                }
+            }
+            "hide" => {
+                if let Some(window) = app.get_window("main") {
+                    let _ = window.hide();
+                }
+            }
+            "quit" => {
+                let state = app.state::<Arc<PythonServer>>();
+                // `app.exit` is a hard exit that skips the window-close/RunEvent
+                // negotiation the hide-to-tray behavior relies on, so it would
+                // otherwise never trigger a graceful backend shutdown.
+                graceful_shutdown(&state);
+                app.exit(0);
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+#[tauri::command]
+fn server_status(state: tauri::State<Arc<PythonServer>>) -> bool {
+    state.is_running()
+}
+
+/// Kills and respawns the backend on the same port. The port is not reserved
+/// between `kill()` and the new spawn; keeping it stable means the frontend's cached `__FIDU_PORT__`/`get_server_port`
+/// value is still correct after a restart instead of silently going stale.
+#[tauri::command]
+fn restart_server(state: tauri::State<Arc<PythonServer>>) {
+    state.kill();
+    start_python_server(state.inner().clone());
+}
+
+#[tauri::command]
+fn get_server_port(state: tauri::State<Arc<PythonServer>>) -> u16 {
+    state.port()
+}
+
+/// Asks the backend to flush any in-flight writes without stopping it. Intended
+/// to be called by the frontend just before it closes.
+#[tauri::command]
+fn flush_now(state: tauri::State<Arc<PythonServer>>) {
+    if state.is_running() {
+        send_backend_post(state.port(), "/flush");
+    }
+}
+
 fn main() {
-  tauri::Builder::default()
-    .setup(|app| {
-      start_python_server();
-      Ok(())
-    })
-    .invoke_handler(tauri::generate_handler![start_python_server])
-    .run(tauri::generate_context!())
-    .expect("error while running tauri application");
+    let port = allocate_port().expect("Failed to allocate a port for the Python server");
+    let python_server = Arc::new(PythonServer::new(port));
+
+    let app = tauri::Builder::default()
+        .manage(python_server.clone())
+        .system_tray(SystemTray::new().with_menu(build_tray_menu()))
+        .on_system_tray_event(on_tray_event)
+        .setup(move |app| {
+            start_python_server(python_server.clone());
+
+            if let Some(main_window) = app.get_window("main") {
+                main_window.hide()?;
+
+                let quit_state = python_server.clone();
+                let window_to_hide = main_window.clone();
+                main_window.on_window_event(move |event| {
+                    if let WindowEvent::CloseRequested { api, .. } = event {
+                        if !quit_state.quitting.load(Ordering::SeqCst) {
+                            api.prevent_close();
+                            let _ = window_to_hide.hide();
+                        }
+                    }
+                });
+            }
+            WindowBuilder::new(
+                app,
+                "splashscreen",
+                WindowUrl::App("splashscreen.html".into()),
+            )
+            .build()?;
+
+            let app_handle = app.handle();
+            let server_state = python_server.clone();
+            std::thread::spawn(move || {
+                let port = server_state.port();
+                if wait_for_server_ready(port) {
+                    if let Some(main_window) = app_handle.get_window("main") {
+                        let _ = main_window.eval(&format!("window.__FIDU_PORT__ = {port};"));
+                    }
+                    // Carry the port in the payload too, so a listener doesn't have to
+                    // race the `eval` above to read `window.__FIDU_PORT__`.
+                    app_handle.emit_all("server-ready", port).ok();
+                } else {
+                    eprintln!(
+                        "Python server did not become healthy within {HEALTH_CHECK_TIMEOUT:?}"
+                    );
+                    // Distinguishable from "server-ready" so the frontend can show an
+                    // error state instead of assuming the backend is reachable.
+                    app_handle.emit_all("server-failed", ()).ok();
+                }
+                if let Some(splashscreen) = app_handle.get_window("splashscreen") {
+                    let _ = splashscreen.close();
+                }
+                if let Some(main_window) = app_handle.get_window("main") {
+                    let _ = main_window.show();
+                }
+            });
+
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            server_status,
+            restart_server,
+            get_server_port,
+            flush_now
+        ])
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application");
+
+    app.run(|app_handle, event| match event {
+        RunEvent::ExitRequested { .. } => {
+            let state = app_handle.state::<Arc<PythonServer>>();
+            graceful_shutdown(&state);
+        }
+        RunEvent::Exit => {
+            // Backstop: a no-op if `graceful_shutdown` already stopped the server.
+            let state = app_handle.state::<Arc<PythonServer>>();
+            state.kill();
+        }
+        _ => {}
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn healthy_response_accepts_200_on_either_http_version() {
+        assert!(is_healthy_response("HTTP/1.1 200 OK\r\n\r\n"));
+        assert!(is_healthy_response("HTTP/1.0 200 OK\r\n\r\n"));
+    }
+
+    #[test]
+    fn healthy_response_rejects_non_200() {
+        assert!(!is_healthy_response("HTTP/1.1 404 Not Found\r\n\r\n"));
+        assert!(!is_healthy_response("HTTP/1.1 500 Internal Server Error\r\n\r\n"));
+        assert!(!is_healthy_response(""));
+    }
+
+    #[test]
+    fn backoff_doubles_and_caps_at_max() {
+        let mut backoff = Duration::from_secs(1);
+        backoff = next_backoff(backoff);
+        assert_eq!(backoff, Duration::from_secs(2));
+        backoff = next_backoff(backoff);
+        assert_eq!(backoff, Duration::from_secs(4));
+
+        // Keep doubling well past MAX_BACKOFF; it should never exceed it.
+        for _ in 0..10 {
+            backoff = next_backoff(backoff);
+        }
+        assert_eq!(backoff, MAX_BACKOFF);
+    }
+
+    #[test]
+    fn later_generation_supersedes_earlier_one() {
+        let state = PythonServer::new(0);
+
+        let first = state.claim_generation();
+        assert!(!state.is_superseded(first));
+
+        let second = state.claim_generation();
+        assert!(state.is_superseded(first));
+        assert!(!state.is_superseded(second));
+    }
 }